@@ -1,7 +1,10 @@
 use std::convert::TryInto;
 
 use bigi::Bigi;
-use bigi_ecc::{point, Point};
+use bigi_ecc::{point, Point, CurveTrait};
+use bigi_ecc::schemas::Schema;
+
+use crate::error::Error;
 
 
 /// Converts a string to a byte array with fixed size.
@@ -19,23 +22,60 @@ pub fn str_to_bytes_sized<const L: usize>(s: &str) -> [u8; L] {
 
 
 /// Converts bytes to a string. Trailing zeros will not affect on the result.
-pub fn str_from_bytes(bytes: &[u8]) -> String {
+/// Returns an error if the bytes are not valid UTF-8.
+pub fn try_str_from_bytes(bytes: &[u8]) -> Result<String, Error> {
     let mut bytes_truncated: Vec<u8> =
         bytes.to_vec().into_iter().rev().skip_while(|&x| x == 0u8).collect();
     bytes_truncated.reverse();
-    String::from_utf8(bytes_truncated).unwrap()
+    String::from_utf8(bytes_truncated).map_err(|_| Error::InvalidUtf8)
+}
+
+
+/// Converts bytes to a string. Trailing zeros will not affect on the result.
+/// Panics if the bytes are not valid UTF-8, see `try_str_from_bytes` for a
+/// fallible version.
+pub fn str_from_bytes(bytes: &[u8]) -> String {
+    try_str_from_bytes(bytes).unwrap()
 }
 
 
 /// Converts HEX number representation to a vector of bytes.
-pub fn hex_to_bytes_vec(hex: &str) -> Vec<u8> {
+/// Returns an error if the string has an odd number of digits or contains
+/// a non-hex character.
+pub fn try_hex_to_bytes_vec(hex: &str) -> Result<Vec<u8>, Error> {
+    if hex.len() % 2 != 0 {
+        return Err(Error::OddHexLength);
+    }
+    if !hex.bytes().all(|c| c.is_ascii_hexdigit()) {
+        return Err(Error::InvalidHexDigit);
+    }
     (0..hex.len()).step_by(2).rev().map(
-        |i| u8::from_str_radix(&hex[i..(i + 2)], 16).unwrap()
+        |i| u8::from_str_radix(&hex[i..(i + 2)], 16)
+            .map_err(|_| Error::InvalidHexDigit)
     ).collect()
 }
 
 
+/// Converts HEX number representation to a vector of bytes.
+/// Panics on malformed input, see `try_hex_to_bytes_vec` for a fallible
+/// version.
+pub fn hex_to_bytes_vec(hex: &str) -> Vec<u8> {
+    try_hex_to_bytes_vec(hex).unwrap()
+}
+
+
+/// Converts HEX number representation to a bytes array with fixed size.
+/// Returns an error if the string is malformed or does not decode to exactly
+/// `L` bytes.
+pub fn try_hex_to_bytes<const L: usize>(hex: &str) -> Result<[u8; L], Error> {
+    let bytes = try_hex_to_bytes_vec(hex)?;
+    let found = bytes.len();
+    bytes.try_into().map_err(|_| Error::InvalidLength { expected: L, found })
+}
+
+
 /// Converts HEX number representation to a bytes array with fixed size.
+/// Panics on malformed input, see `try_hex_to_bytes` for a fallible version.
 /// ```rust
 /// use hashstorage_utils::convert::hex_to_bytes;
 ///
@@ -43,7 +83,7 @@ pub fn hex_to_bytes_vec(hex: &str) -> Vec<u8> {
 /// assert_eq!(bytes, [139u8, 193]);
 /// ```
 pub fn hex_to_bytes<const L: usize>(hex: &str) -> [u8; L] {
-    hex_to_bytes_vec(hex).try_into().unwrap()
+    try_hex_to_bytes(hex).unwrap()
 }
 
 
@@ -79,15 +119,146 @@ pub fn public_key_to_bytes(p: &Point<4>) -> [u8; 64] {
 }
 
 
-/// Converts an array of 64 bytes to a point on an elliptic curve that can be
-/// represented as a public key.
+/// Converts a byte slice to a point on an elliptic curve that can be
+/// represented as a public key. Returns an error if the slice is not
+/// exactly 64 bytes long.
 /// Note: this function will not work correctly for zero point,
 /// but in practice zero public key does not make any sense.
-pub fn public_key_from_bytes(bytes: &[u8; 64]) -> Point<4> {
-    point!(
+pub fn try_public_key_from_bytes(bytes: &[u8]) -> Result<Point<4>, Error> {
+    if bytes.len() != 64 {
+        return Err(Error::InvalidLength { expected: 64, found: bytes.len() });
+    }
+    Ok(point!(
         Bigi::<4>::from_bytes(&bytes[..32]),
         Bigi::<4>::from_bytes(&bytes[32..])
-    )
+    ))
+}
+
+
+/// Converts an array of 64 bytes to a point on an elliptic curve that can be
+/// represented as a public key. Panics if the input is malformed, see
+/// `try_public_key_from_bytes` for a fallible version.
+/// Note: this function will not work correctly for zero point,
+/// but in practice zero public key does not make any sense.
+pub fn public_key_from_bytes(bytes: &[u8; 64]) -> Point<4> {
+    try_public_key_from_bytes(bytes).unwrap()
+}
+
+
+/// Converts a public key point to its compressed SEC1 representation: a
+/// 1-byte parity prefix (`0x02` if `y` is even, `0x03` if `y` is odd)
+/// followed by the 32-byte `x` coordinate. This halves the storage needed
+/// for a public key compared to `public_key_to_bytes`.
+/// Note: this function will not work correctly for zero point,
+/// but in practice zero public key does not make any sense.
+pub fn public_key_to_bytes_compressed(p: &Point<4>) -> [u8; 33] {
+    let prefix: u8 = if p.y.is_even() { 0x02 } else { 0x03 };
+    let mut bytes = [0u8; 33];
+    bytes[0] = prefix;
+    bytes[1..].copy_from_slice(&p.x.to_bytes()[..32]);
+    bytes
+}
+
+
+/// Recovers a public key point from its compressed SEC1 representation by
+/// solving the curve equation `y^2 = x^3 + a*x + b (mod p)` for `y` (via a
+/// modular square root) and selecting the root whose parity matches the
+/// prefix byte. Returns an error if the prefix is invalid, `x` is out of the
+/// field range, or `x` is not the `x` coordinate of any point on the curve.
+pub fn public_key_from_bytes_compressed<T: CurveTrait<4>>(
+            schema: &Schema<T, 4>, bytes: &[u8; 33]
+        ) -> Result<Point<4>, Error> {
+    let prefix = bytes[0];
+    if prefix != 0x02 && prefix != 0x03 {
+        return Err(Error::InvalidPrefix);
+    }
+
+    let x = Bigi::<4>::from_bytes(&bytes[1..]);
+    let p = schema.curve.get_p();
+
+    if x >= p {
+        return Err(Error::PointNotOnCurve);
+    }
+
+    let a = schema.curve.get_a();
+    let b = schema.curve.get_b();
+
+    // y^2 = x^3 + a*x + b (mod p)
+    let rhs = x.power_mod(&Bigi::<4>::from(3u64), &p)
+        .add_mod(&a.mul_mod(&x, &p), &p)
+        .add_mod(&b, &p);
+
+    let y = mod_sqrt(&rhs, &p).ok_or(Error::PointNotOnCurve)?;
+
+    let wants_even = prefix == 0x02;
+    let y = if y.is_even() == wants_even { y } else { p - y };
+
+    Ok(point!(x, y))
+}
+
+
+/// Computes a square root of `n` modulo the prime `p` using the
+/// Tonelli-Shanks algorithm. Returns `None` if `n` is not a quadratic
+/// residue modulo `p`.
+pub(crate) fn mod_sqrt(n: &Bigi<4>, p: &Bigi<4>) -> Option<Bigi<4>> {
+    let zero = Bigi::<4>::from(0u64);
+    let one = Bigi::<4>::from(1u64);
+    let two = Bigi::<4>::from(2u64);
+
+    if *n == zero {
+        return Some(zero);
+    }
+
+    // Euler's criterion: n must be a quadratic residue modulo p.
+    if n.power_mod(&((*p - one) / two), p) != one {
+        return None;
+    }
+
+    // Factor p - 1 = q * 2^s with q odd.
+    let mut q = *p - one;
+    let mut s = 0u32;
+    while q % two == zero {
+        q = q / two;
+        s += 1;
+    }
+
+    if s == 1 {
+        // p % 4 == 3: the fast path sqrt = n^((p + 1) / 4) mod p.
+        return Some(n.power_mod(&((*p + one) / (two * two)), p));
+    }
+
+    // Find a quadratic non-residue z.
+    let mut z = two;
+    while z.power_mod(&((*p - one) / two), p) != *p - one {
+        z = z + one;
+    }
+
+    let mut m = s;
+    let mut c = z.power_mod(&q, p);
+    let mut t = n.power_mod(&q, p);
+    let mut r = n.power_mod(&((q + one) / two), p);
+
+    while t != one {
+        // Find the least i, 0 < i < m, such that t^(2^i) = 1.
+        let mut i = 0u32;
+        let mut t2i = t;
+        while t2i != one {
+            t2i = t2i.mul_mod(&t2i, p);
+            i += 1;
+        }
+
+        let mut b = c;
+        for _ in 0..(m - i - 1) {
+            b = b.mul_mod(&b, p);
+        }
+
+        m = i;
+        c = b.mul_mod(&b, p);
+        t = t.mul_mod(&c, p);
+        r = r.mul_mod(&b, p);
+    }
+
+    Some(r)
 }
 
 
@@ -101,11 +272,142 @@ pub fn signature_to_bytes(signature: &(Bigi<4>, Bigi<4>)) -> [u8; 64] {
 }
 
 
-/// Converts an array of 64 bytes to a pair of 256-bit integers (type Bigi<4>)
-/// that can be represented as a signature.
-pub fn signature_from_bytes(bytes: &[u8; 64]) -> (Bigi<4>, Bigi<4>) {
-    (
+/// Converts a byte slice to a pair of 256-bit integers (type Bigi<4>) that
+/// can be represented as a signature. Returns an error if the slice is not
+/// exactly 64 bytes long.
+pub fn try_signature_from_bytes(bytes: &[u8]) -> Result<(Bigi<4>, Bigi<4>), Error> {
+    if bytes.len() != 64 {
+        return Err(Error::InvalidLength { expected: 64, found: bytes.len() });
+    }
+    Ok((
         Bigi::<4>::from_bytes(&bytes[..32]),
         Bigi::<4>::from_bytes(&bytes[32..])
-    )
+    ))
+}
+
+
+/// Converts an array of 64 bytes to a pair of 256-bit integers (type Bigi<4>)
+/// that can be represented as a signature. Panics if the input is malformed,
+/// see `try_signature_from_bytes` for a fallible version.
+pub fn signature_from_bytes(bytes: &[u8; 64]) -> (Bigi<4>, Bigi<4>) {
+    try_signature_from_bytes(bytes).unwrap()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use bigi_ecc::schemas::load_secp256k1;
+
+    use super::*;
+
+    #[test]
+    fn test_try_str_from_bytes_ok() {
+        let bytes = str_to_bytes_sized::<16>("hello world");
+        assert_eq!(try_str_from_bytes(&bytes), Ok("hello world".to_string()));
+    }
+
+    #[test]
+    fn test_try_str_from_bytes_invalid_utf8() {
+        let bytes = [0xFFu8, 0xFE, 0xFD];
+        assert_eq!(try_str_from_bytes(&bytes), Err(Error::InvalidUtf8));
+    }
+
+    #[test]
+    fn test_try_hex_to_bytes_vec_ok() {
+        assert_eq!(try_hex_to_bytes_vec("C18B"), Ok(vec![139u8, 193]));
+    }
+
+    #[test]
+    fn test_try_hex_to_bytes_vec_odd_length() {
+        assert_eq!(try_hex_to_bytes_vec("ABC"), Err(Error::OddHexLength));
+    }
+
+    #[test]
+    fn test_try_hex_to_bytes_vec_invalid_digit() {
+        assert_eq!(try_hex_to_bytes_vec("ZZ"), Err(Error::InvalidHexDigit));
+    }
+
+    #[test]
+    fn test_try_hex_to_bytes_ok() {
+        let bytes: [u8; 2] = try_hex_to_bytes("C18B").unwrap();
+        assert_eq!(bytes, [139u8, 193]);
+    }
+
+    #[test]
+    fn test_try_hex_to_bytes_wrong_length() {
+        let result: Result<[u8; 4], Error> = try_hex_to_bytes("C18B");
+        assert_eq!(result, Err(Error::InvalidLength { expected: 4, found: 2 }));
+    }
+
+    #[test]
+    fn test_try_public_key_from_bytes_ok() {
+        let p = point!(Bigi::<4>::from(1u64), Bigi::<4>::from(2u64));
+        let bytes = public_key_to_bytes(&p);
+        assert_eq!(try_public_key_from_bytes(&bytes), Ok(p));
+    }
+
+    #[test]
+    fn test_try_public_key_from_bytes_wrong_length() {
+        let bytes = [0u8; 63];
+        assert_eq!(
+            try_public_key_from_bytes(&bytes),
+            Err(Error::InvalidLength { expected: 64, found: 63 })
+        );
+    }
+
+    #[test]
+    fn test_try_signature_from_bytes_ok() {
+        let signature = (Bigi::<4>::from(3u64), Bigi::<4>::from(4u64));
+        let bytes = signature_to_bytes(&signature);
+        assert_eq!(try_signature_from_bytes(&bytes), Ok(signature));
+    }
+
+    #[test]
+    fn test_try_signature_from_bytes_wrong_length() {
+        let bytes = [0u8; 65];
+        assert_eq!(
+            try_signature_from_bytes(&bytes),
+            Err(Error::InvalidLength { expected: 64, found: 65 })
+        );
+    }
+
+    #[test]
+    fn test_mod_sqrt_p_equiv_1_mod_4() {
+        // secp256k1's field prime is 3 mod 4, so this exercises the general
+        // Tonelli-Shanks branch that the crate's only schema never reaches.
+        let p = Bigi::<4>::from(13u64);
+
+        let root = mod_sqrt(&Bigi::<4>::from(4u64), &p).unwrap();
+        assert_eq!(root.mul_mod(&root, &p), Bigi::<4>::from(4u64));
+
+        assert_eq!(mod_sqrt(&Bigi::<4>::from(2u64), &p), None);
+    }
+
+    #[test]
+    fn test_public_key_compressed_round_trip() {
+        let mut rng = rand::thread_rng();
+        let schema = load_secp256k1();
+
+        // Generate keys until both parity branches of the prefix byte have
+        // been exercised against the real curve parameters.
+        let mut seen_even = false;
+        let mut seen_odd = false;
+
+        for _ in 0..16 {
+            let (_, public_key) = schema.generate_pair(&mut rng);
+            let compressed = public_key_to_bytes_compressed(&public_key);
+
+            match compressed[0] {
+                0x02 => seen_even = true,
+                0x03 => seen_odd = true,
+                prefix => panic!("unexpected prefix byte {:#04x}", prefix),
+            }
+
+            let decompressed =
+                public_key_from_bytes_compressed(&schema, &compressed).unwrap();
+            assert_eq!(decompressed, public_key);
+        }
+
+        assert!(seen_even && seen_odd);
+    }
 }
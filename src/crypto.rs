@@ -35,12 +35,26 @@ use std::convert::TryInto;
 
 use rand::Rng;
 use sha2::{Sha256, Digest};
-use bigi_ecc::CurveTrait;
+use aes::Aes128;
+use ctr::Ctr128BE;
+use ctr::cipher::{KeyIvInit, StreamCipher};
+use hmac::{Hmac, Mac};
+use bigi::Bigi;
+use bigi_ecc::{point, CurveTrait};
 use bigi_ecc::schemas::Schema;
 use bigi_ecc::ecdsa::{check_signature as ecdsa_check_signature,
                       build_signature as ecdsa_build_signature};
 
 use crate::convert::*;
+use crate::error::Error;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Length in bytes of the ECIES ephemeral public key prefix, the AES-CTR IV
+/// and the HMAC-SHA256 tag suffix of an ECIES ciphertext, respectively.
+const ECIES_PUBLIC_KEY_LEN: usize = 64;
+const ECIES_IV_LEN: usize = 16;
+const ECIES_TAG_LEN: usize = 32;
 
 
 /// Creates a signature of a hashstorage block by given: group, key, version
@@ -118,6 +132,331 @@ pub fn check_pair<T: CurveTrait<4>>(
 }
 
 
+/// Computes the ECDH shared secret between `my_private` and `their_public`
+/// on the curve described by `schema`: `S = my_private * their_public`,
+/// reduced to a uniformly distributed 32-byte key via `sha256_hash` of the
+/// `x` coordinate of `S`. Two participants can each run this with their own
+/// private key and the other's public key to agree on the same secret
+/// without an interactive handshake. Returns an error if `S` is the
+/// identity point, which happens only for degenerate inputs such as a zero
+/// private key.
+pub fn ecdh_shared_secret<T: CurveTrait<4>>(
+            schema: &Schema<T, 4>,
+            my_private: &[u8; 32], their_public: &[u8; 64]
+        ) -> Result<[u8; 32], Error> {
+    let my_scalar = private_key_from_bytes(my_private);
+    let their_point = public_key_from_bytes(their_public);
+    let shared_point = schema.curve.mul(&their_point, &my_scalar);
+
+    let zero = Bigi::<4>::from(0u64);
+    if shared_point.x == zero && shared_point.y == zero {
+        return Err(Error::IdentityPoint);
+    }
+
+    Ok(sha256_hash(&shared_point.x.to_bytes()[..32]))
+}
+
+
+/// Derives `length` bytes of key material from an ECDH `shared_secret`
+/// using a SHA256-based counter KDF, as commonly used to turn a raw ECDH
+/// secret into separate cipher and MAC keys.
+fn kdf(shared_secret: &[u8], length: usize) -> Vec<u8> {
+    let mut output = Vec::with_capacity(length);
+    let mut counter: u32 = 1;
+    while output.len() < length {
+        let mut hasher = Sha256::new();
+        hasher.update(counter.to_be_bytes());
+        hasher.update(shared_secret);
+        output.extend_from_slice(&hasher.finalize());
+        counter += 1;
+    }
+    output.truncate(length);
+    output
+}
+
+
+/// Encrypts `plaintext` for the holder of `recipient_public` using ECIES: an
+/// ephemeral key pair is generated, its shared point with the recipient's
+/// public key seeds a KDF that derives an AES-128-CTR key and an
+/// HMAC-SHA256 key, and the ciphertext is tagged with the MAC. The output
+/// layout is `ephemeral public key (64 bytes) || IV (16 bytes) ||
+/// ciphertext || tag (32 bytes)`. Returns an error if `recipient_public`
+/// does not decode to a point that yields a valid ECDH shared secret (e.g.
+/// the identity point), since that key may come from an untrusted caller.
+pub fn encrypt<T: CurveTrait<4>, R: Rng + ?Sized>(
+            rng: &mut R, schema: &Schema<T, 4>,
+            recipient_public: &[u8; 64], plaintext: &[u8]
+        ) -> Result<Vec<u8>, Error> {
+    let (ephemeral_private, ephemeral_public) = generate_pair(rng, schema);
+
+    let shared_secret = ecdh_shared_secret(schema, &ephemeral_private, recipient_public)?;
+
+    let keys = kdf(&shared_secret, 48);
+    let (aes_key, mac_key) = keys.split_at(16);
+
+    let mut iv = [0u8; ECIES_IV_LEN];
+    rng.fill(&mut iv);
+
+    let mut ciphertext = plaintext.to_vec();
+    let mut cipher = Ctr128BE::<Aes128>::new(aes_key.into(), &iv.into());
+    cipher.apply_keystream(&mut ciphertext);
+
+    let mut mac = HmacSha256::new_from_slice(mac_key).unwrap();
+    mac.update(&iv);
+    mac.update(&ciphertext);
+    let tag = mac.finalize().into_bytes();
+
+    Ok([&ephemeral_public[..], &iv[..], &ciphertext[..], &tag[..]].concat())
+}
+
+
+/// Decrypts an ECIES ciphertext produced by `encrypt` using the recipient's
+/// private key. Returns an error if the ciphertext is too short to contain
+/// the ephemeral public key, IV and tag, or if the MAC does not verify.
+pub fn decrypt<T: CurveTrait<4>>(
+            schema: &Schema<T, 4>,
+            recipient_private: &[u8; 32], ciphertext: &[u8]
+        ) -> Result<Vec<u8>, Error> {
+    let header_len = ECIES_PUBLIC_KEY_LEN + ECIES_IV_LEN + ECIES_TAG_LEN;
+    if ciphertext.len() < header_len {
+        return Err(Error::InvalidLength {
+            expected: header_len, found: ciphertext.len()
+        });
+    }
+
+    let ephemeral_public: [u8; 64] =
+        ciphertext[..ECIES_PUBLIC_KEY_LEN].try_into().unwrap();
+    let iv: [u8; ECIES_IV_LEN] = ciphertext[
+        ECIES_PUBLIC_KEY_LEN..(ECIES_PUBLIC_KEY_LEN + ECIES_IV_LEN)
+    ].try_into().unwrap();
+    let tag = &ciphertext[(ciphertext.len() - ECIES_TAG_LEN)..];
+    let body = &ciphertext[
+        (ECIES_PUBLIC_KEY_LEN + ECIES_IV_LEN)..(ciphertext.len() - ECIES_TAG_LEN)
+    ];
+
+    let shared_secret = ecdh_shared_secret(schema, recipient_private, &ephemeral_public)?;
+
+    let keys = kdf(&shared_secret, 48);
+    let (aes_key, mac_key) = keys.split_at(16);
+
+    let mut mac = HmacSha256::new_from_slice(mac_key).unwrap();
+    mac.update(&iv);
+    mac.update(body);
+    mac.verify_slice(tag).map_err(|_| Error::InvalidMac)?;
+
+    let mut plaintext = body.to_vec();
+    let mut cipher = Ctr128BE::<Aes128>::new(aes_key.into(), &iv.into());
+    cipher.apply_keystream(&mut plaintext);
+
+    Ok(plaintext)
+}
+
+
+/// Computes HMAC-SHA256 of `data` under `key`.
+fn hmac_sha256(key: &[u8], data: &[u8]) -> [u8; 32] {
+    let mut mac = HmacSha256::new_from_slice(key).unwrap();
+    mac.update(data);
+    mac.finalize().into_bytes().into()
+}
+
+
+/// Seeds the RFC 6979 `K`/`V` state from the big-endian octets of the
+/// private key and of the message hash `h`, running the two HMAC update
+/// rounds prescribed by the RFC.
+fn rfc6979_init(private_key: &[u8; 32], h: &[u8; 32]) -> ([u8; 32], [u8; 32]) {
+    let mut v = [0x01u8; 32];
+    let mut k = [0x00u8; 32];
+
+    k = hmac_sha256(&k, &[&v[..], &[0x00], &private_key[..], &h[..]].concat());
+    v = hmac_sha256(&k, &v);
+    k = hmac_sha256(&k, &[&v[..], &[0x01], &private_key[..], &h[..]].concat());
+    v = hmac_sha256(&k, &v);
+
+    (k, v)
+}
+
+
+/// Generates the next RFC 6979 nonce candidate from the `K`/`V` state,
+/// advancing `K`/`V` in place. Candidates are `V` read as a big-endian
+/// integer and are drawn until one falls in `[1, n - 1]`. Every candidate,
+/// returned or not, is followed by the RFC's re-seed of `K`/`V`, so calling
+/// this again after a rejected `r`/`s` resumes from the same chain instead
+/// of restarting it.
+fn rfc6979_next(k: &mut [u8; 32], v: &mut [u8; 32], n: &Bigi<4>) -> Bigi<4> {
+    let zero = Bigi::<4>::from(0u64);
+
+    loop {
+        *v = hmac_sha256(k, v);
+        let mut t = *v;
+        t.reverse();
+        let candidate = Bigi::<4>::from_bytes(&t);
+        *k = hmac_sha256(k, &[&v[..], &[0x00]].concat());
+        *v = hmac_sha256(k, v);
+        if candidate != zero && candidate < *n {
+            return candidate;
+        }
+    }
+}
+
+
+/// Creates a signature of a hashstorage block by given: group, key, version
+/// and data, using a nonce `k` derived deterministically from the private
+/// key and the message hash per RFC 6979, instead of drawing it from an
+/// RNG. The same inputs always produce the same signature, which removes
+/// the dependence on entropy quality that `build_signature` has.
+pub fn build_signature_deterministic<T: CurveTrait<4>>(
+            schema: &Schema<T, 4>,
+            private_key: &[u8; 32], group: &[u8; 32], key: &[u8; 32],
+            version: u64, data: &[u8]
+        ) -> [u8; 64] {
+    let private_bigi = private_key_from_bytes(private_key);
+    let hash = sha256_pack(group, key, version, data);
+    let e = Bigi::<4>::from_bytes(&hash) % schema.n;
+    let zero = Bigi::<4>::from(0u64);
+
+    // RFC 6979 encodes integers big-endian; this crate stores them
+    // little-endian.
+    let mut x_octets = *private_key;
+    x_octets.reverse();
+    let mut h_octets: [u8; 32] = e.to_bytes()[..32].try_into().unwrap();
+    h_octets.reverse();
+
+    let (mut k_state, mut v_state) = rfc6979_init(&x_octets, &h_octets);
+
+    loop {
+        let k = rfc6979_next(&mut k_state, &mut v_state, &schema.n);
+        let point_r = schema.get_point(&k);
+        let r = point_r.x % schema.n;
+        if r == zero {
+            continue;
+        }
+
+        let s = k.invmod(&schema.n).mul_mod(
+            &e.add_mod(&r.mul_mod(&private_bigi, &schema.n), &schema.n),
+            &schema.n
+        );
+        if s == zero {
+            continue;
+        }
+
+        return signature_to_bytes(&(r, s));
+    }
+}
+
+
+/// Generates a random nonce `k` in the range `[1, n - 1]`, retrying if the
+/// random draw lands on zero.
+fn random_nonce<R: Rng + ?Sized>(rng: &mut R, n: &Bigi<4>) -> Bigi<4> {
+    let zero = Bigi::<4>::from(0u64);
+    loop {
+        let k = Bigi::<4>::gen_random(rng, n);
+        if k != zero {
+            return k;
+        }
+    }
+}
+
+
+/// Creates a recoverable signature of a hashstorage block by given: group,
+/// key, version and data. The result is the usual 64-byte `(r, s)` signature
+/// with a 1-byte recovery id appended, so the signer's public key can later
+/// be reconstructed from the signature alone with `recover_public_key`
+/// instead of being transmitted or stored separately.
+pub fn build_signature_recoverable<T: CurveTrait<4>, R: Rng + ?Sized>(
+            rng: &mut R, schema: &Schema<T, 4>,
+            private_key: &[u8; 32], group: &[u8; 32], key: &[u8; 32],
+            version: u64, data: &[u8]
+        ) -> [u8; 65] {
+    let private_bigi = private_key_from_bytes(private_key);
+    let hash = sha256_pack(group, key, version, data);
+    let e = Bigi::<4>::from_bytes(&hash) % schema.n;
+
+    loop {
+        let k = random_nonce(rng, &schema.n);
+        let point_r = schema.get_point(&k);
+        let r = point_r.x % schema.n;
+
+        let zero = Bigi::<4>::from(0u64);
+        if r == zero {
+            continue;
+        }
+
+        let s = k.invmod(&schema.n).mul_mod(
+            &e.add_mod(&r.mul_mod(&private_bigi, &schema.n), &schema.n),
+            &schema.n
+        );
+        if s == zero {
+            continue;
+        }
+
+        let recid: u8 = (!point_r.y.is_even() as u8)
+            | (((point_r.x >= schema.n) as u8) << 1);
+
+        let mut bytes = [0u8; 65];
+        bytes[..64].copy_from_slice(&signature_to_bytes(&(r, s)));
+        bytes[64] = recid;
+        return bytes;
+    }
+}
+
+
+/// Recovers the signer's public key from a recoverable signature and the
+/// hashstorage block it was made over: group, key, version and data.
+/// Returns an error if the recovery id refers to a point outside the field,
+/// or if the reconstructed point does not lie on the curve.
+pub fn recover_public_key<T: CurveTrait<4>>(
+            schema: &Schema<T, 4>, signature: &[u8; 65],
+            group: &[u8; 32], key: &[u8; 32], version: u64, data: &[u8]
+        ) -> Result<[u8; 64], Error> {
+    let (r, s) = signature_from_bytes(&signature[..64].try_into().unwrap());
+    let recid = signature[64];
+
+    let hash = sha256_pack(group, key, version, data);
+    let e = Bigi::<4>::from_bytes(&hash) % schema.n;
+
+    let is_second_key = (recid >> 1) & 1 == 1;
+    // Checked before adding `n` so that `r + n` cannot overflow 256 bits.
+    let p = schema.curve.get_p();
+    if is_second_key && (schema.n >= p || r >= p - schema.n) {
+        return Err(Error::PointNotOnCurve);
+    }
+    let x = if is_second_key { r + schema.n } else { r };
+    if x >= schema.curve.get_p() {
+        return Err(Error::PointNotOnCurve);
+    }
+
+    let a = schema.curve.get_a();
+    let b = schema.curve.get_b();
+    let p = schema.curve.get_p();
+    let rhs = x.power_mod(&Bigi::<4>::from(3u64), &p)
+        .add_mod(&a.mul_mod(&x, &p), &p)
+        .add_mod(&b, &p);
+    let y = crate::convert::mod_sqrt(&rhs, &p).ok_or(Error::PointNotOnCurve)?;
+
+    let wants_even = recid & 1 == 0;
+    let y = if y.is_even() == wants_even { y } else { p - y };
+    let point_r = point!(x, y);
+
+    if !schema.curve.contains(&point_r) {
+        return Err(Error::PointNotOnCurve);
+    }
+
+    let r_inv = r.invmod(&schema.n);
+    let neg_e = (schema.n - e) % schema.n;
+
+    let sr = schema.curve.mul(&point_r, &s);
+    let neg_eg = schema.get_point(&neg_e);
+    let q = schema.curve.mul(&schema.curve.add(&sr, &neg_eg), &r_inv);
+
+    let zero = Bigi::<4>::from(0u64);
+    if q.x == zero && q.y == zero {
+        return Err(Error::IdentityPoint);
+    }
+
+    Ok(public_key_to_bytes(&q))
+}
+
+
 #[cfg(test)]
 mod tests {
     use bigi_ecc::schemas::load_secp256k1;
@@ -152,4 +491,76 @@ mod tests {
         );
         assert_eq!(result, true);
     }
+
+    #[test]
+    fn test_signature_recoverable() {
+        let mut rng = rand::thread_rng();
+        let schema = load_secp256k1();
+        let (private_key, public_key) = generate_pair(&mut rng, &schema);
+
+        let group: [u8; 32] = str_to_bytes_sized("my group");
+        let key: [u8; 32] = str_to_bytes_sized("my key");
+        let version: u64 = 1;
+        let data = b"my test data";
+
+        let signature = build_signature_recoverable(
+            &mut rng, &schema, &private_key, &group, &key, version, data
+        );
+
+        let recovered = recover_public_key(
+            &schema, &signature, &group, &key, version, data
+        ).unwrap();
+        assert_eq!(recovered, public_key);
+    }
+
+    #[test]
+    fn test_ecies() {
+        let mut rng = rand::thread_rng();
+        let schema = load_secp256k1();
+        let (private_key, public_key) = generate_pair(&mut rng, &schema);
+
+        let plaintext = b"my secret block data";
+        let ciphertext = encrypt(&mut rng, &schema, &public_key, plaintext).unwrap();
+        let decrypted = decrypt(&schema, &private_key, &ciphertext).unwrap();
+
+        assert_eq!(decrypted, plaintext);
+    }
+
+    #[test]
+    fn test_ecdh_shared_secret() {
+        let mut rng = rand::thread_rng();
+        let schema = load_secp256k1();
+        let (private_key_1, public_key_1) = generate_pair(&mut rng, &schema);
+        let (private_key_2, public_key_2) = generate_pair(&mut rng, &schema);
+
+        let secret_1 = ecdh_shared_secret(&schema, &private_key_1, &public_key_2).unwrap();
+        let secret_2 = ecdh_shared_secret(&schema, &private_key_2, &public_key_1).unwrap();
+
+        assert_eq!(secret_1, secret_2);
+    }
+
+    #[test]
+    fn test_signature_deterministic() {
+        let mut rng = rand::thread_rng();
+        let schema = load_secp256k1();
+        let (private_key, public_key) = generate_pair(&mut rng, &schema);
+
+        let group: [u8; 32] = str_to_bytes_sized("my group");
+        let key: [u8; 32] = str_to_bytes_sized("my key");
+        let version: u64 = 1;
+        let data = b"my test data";
+
+        let signature_1 = build_signature_deterministic(
+            &schema, &private_key, &group, &key, version, data
+        );
+        let signature_2 = build_signature_deterministic(
+            &schema, &private_key, &group, &key, version, data
+        );
+        assert_eq!(signature_1, signature_2);
+
+        let result = check_signature(
+            &schema, &signature_1, &public_key, &group, &key, version, data
+        );
+        assert_eq!(result, true);
+    }
 }
@@ -0,0 +1,69 @@
+//! Error type returned by the fallible functions of this crate: the
+//! `try_`-prefixed counterparts of the conversion functions and the
+//! cryptographic functions that return a `Result`. These functions sit
+//! at the WASM/JS boundary where the input cannot be trusted, so malformed
+//! data should be reported back as a `Result` rather than causing a panic.
+
+use std::fmt;
+
+/// Describes why a conversion between external and internal representations
+/// has failed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// A byte slice does not have the length required by the target type.
+    InvalidLength { expected: usize, found: usize },
+    /// A HEX string has an odd number of digits, so it cannot be split into
+    /// whole bytes.
+    OddHexLength,
+    /// A HEX string contains a character that is not a valid HEX digit.
+    InvalidHexDigit,
+    /// A byte sequence is not valid UTF-8.
+    InvalidUtf8,
+    /// A compressed public key has a prefix byte other than `0x02`/`0x03`.
+    InvalidPrefix,
+    /// The `x` coordinate of a compressed public key, or the one derived
+    /// from a recoverable signature, is not on the curve, either because it
+    /// is out of the field range or `y` has no square root modulo the field
+    /// prime.
+    PointNotOnCurve,
+    /// The MAC attached to an ECIES ciphertext does not match, meaning the
+    /// ciphertext was tampered with or decrypted with the wrong key.
+    InvalidMac,
+    /// A Diffie-Hellman computation or a public key recovery produced the
+    /// identity (infinity) point, which is neither a shared secret nor a
+    /// public key.
+    IdentityPoint,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidLength { expected, found } => write!(
+                f, "invalid length: expected {} bytes, found {}", expected, found
+            ),
+            Error::OddHexLength => write!(
+                f, "hex string must consist of a whole number of bytes"
+            ),
+            Error::InvalidHexDigit => write!(
+                f, "hex string contains a non-hex digit"
+            ),
+            Error::InvalidUtf8 => write!(
+                f, "bytes do not represent a valid UTF-8 string"
+            ),
+            Error::InvalidPrefix => write!(
+                f, "compressed public key prefix must be 0x02 or 0x03"
+            ),
+            Error::PointNotOnCurve => write!(
+                f, "x coordinate does not correspond to a point on the curve"
+            ),
+            Error::InvalidMac => write!(
+                f, "ciphertext MAC verification failed"
+            ),
+            Error::IdentityPoint => write!(
+                f, "result is the identity point"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
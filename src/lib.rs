@@ -12,3 +12,6 @@
 
 pub mod convert;
 pub mod crypto;
+pub mod error;
+
+pub use error::Error;